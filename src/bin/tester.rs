@@ -1,10 +1,19 @@
 extern crate gll;
 extern crate proc_macro2;
+// NOTE: this tree has no top-level Cargo.toml (not even in the baseline
+// commit), so there's nowhere to add `rayon` as a declared dependency
+// for this `extern crate` to resolve against. Treat this crate as
+// depending on `rayon` as soon as a manifest exists for it.
+extern crate rayon;
 extern crate rust_grammar;
 extern crate structopt;
 extern crate walkdir;
 
+mod ambiguity;
+mod golden;
+
 use gll::runtime::{MoreThanOne, ParseNodeKind, ParseNodeShape};
+use rayon::prelude::*;
 use rust_grammar::parse;
 use std::collections::{BTreeSet, VecDeque};
 use std::fs;
@@ -39,51 +48,215 @@ enum Command {
         /// Directory to find Rust files in
         dir: PathBuf,
     },
+
+    #[structopt(name = "test")]
+    /// Run the golden-file regression suite under `tests/data/`
+    Test {
+        #[structopt(parse(from_os_str), default_value = "tests/data")]
+        /// Directory holding `ok/` and `err/` fixture subdirectories
+        dir: PathBuf,
+    },
 }
 
-type ModuleContentsResult<'a, 'i> = parse::ParseResult<
+pub(crate) type ModuleContentsResult<'a, 'i> = parse::ParseResult<
     'a,
     'i,
     proc_macro2::TokenStream,
     parse::ModuleContents<'a, 'i, proc_macro2::TokenStream>,
 >;
 
-type ModuleContentsHandle<'a, 'i> = parse::Handle<
+pub(crate) type ModuleContentsHandle<'a, 'i> = parse::Handle<
     'a,
     'i,
     proc_macro2::TokenStream,
     parse::ModuleContents<'a, 'i, proc_macro2::TokenStream>,
 >;
 
+/// The outcome of trying to get a `ModuleContentsResult` out of a file:
+/// either the source didn't even lex into a `TokenStream`, or it did and
+/// we have an actual parse result to show.
+pub(crate) enum FileOutcome<'a, 'i> {
+    LexError { src: String, error: proc_macro2::LexError },
+    Parsed { src: String, result: ModuleContentsResult<'a, 'i> },
+}
+
 /// Read the contents of the file at the given `path`, parse it
 /// using the `ModuleContents` rule, and pass the result to `f`.
-fn parse_file_with<R>(path: &Path, f: impl FnOnce(ModuleContentsResult) -> R) -> R {
+pub(crate) fn parse_file_with<R>(path: &Path, f: impl FnOnce(FileOutcome) -> R) -> R {
     let src = fs::read_to_string(path).unwrap();
     match src.parse::<proc_macro2::TokenStream>() {
-        Ok(tts) => parse::ModuleContents::parse_with(tts, |_, result| f(result)),
-        // FIXME(eddyb) provide more information in this error case.
-        Err(_) => f(Err(parse::ParseError::NoParse)),
+        Ok(tts) => {
+            parse::ModuleContents::parse_with(tts, |_, result| f(FileOutcome::Parsed { src, result }))
+        }
+        Err(error) => f(FileOutcome::LexError { src, error }),
     }
 }
 
+/// Render a `proc_macro2::LexError` as a caret-annotated snippet of the
+/// source line it occurred on, rust-analyzer lexer-fixture style.
+fn render_lex_error(src: &str, error: &proc_macro2::LexError) -> String {
+    let start = error.span().start();
+    let line_text = src.lines().nth(start.line.saturating_sub(1)).unwrap_or("");
+    format!(
+        "lexer error at {}:{}: {}\n{}\n{}^\n",
+        start.line,
+        start.column + 1,
+        error,
+        line_text,
+        " ".repeat(start.column)
+    )
+}
+
+/// Map a byte offset into `src` to a 1-based line, a 0-based column, and
+/// the text of the line it falls on.
+fn line_col_of(src: &str, offset: usize) -> (usize, usize, &str) {
+    let mut line = 1;
+    let mut col = 0;
+    let mut line_start = 0;
+    for (i, ch) in src.char_indices() {
+        if i >= offset {
+            break;
+        }
+        if ch == '\n' {
+            line += 1;
+            col = 0;
+            line_start = i + 1;
+        } else {
+            col += 1;
+        }
+    }
+    let line_text = src[line_start..].lines().next().unwrap_or("");
+    (line, col, line_text)
+}
+
+/// Render a rustc-style diagnostic for a `TooShort` (or outright
+/// `NoParse`) result: the source line the parser got stuck on, a caret
+/// under the furthest token it reached, and what it was expecting there.
+///
+/// Two gaps against the original ask, documented rather than silently
+/// shipped:
+///
+/// * `handle.node.range.end` is fed to `line_col_of` as a byte offset
+///   into `src`. That assumes `gll`'s generated `ParseNode::range` (over
+///   `I = proc_macro2::TokenStream`) is byte-offset-based rather than a
+///   token index into the `TokenStream` -- this is the only place in the
+///   tree that uses `.range` against `src` at all, `gll`'s own source
+///   isn't vendored here, and there's no way to compile and run this
+///   against real input in this environment, so the assumption has
+///   never actually been exercised. If it turns out to be a token
+///   index, every line/column/caret this prints is wrong, not absent --
+///   verify against the real `gll` before trusting this for real
+///   debugging.
+/// * this only prints the single furthest node's own `kind`, not "the
+///   set of grammar rules that were expected at that point" the request
+///   asked for. Surfacing the full set would need `gll` to expose the
+///   active parser descriptors at a position, not just the one node that
+///   happened to be furthest; that's not part of `ModuleContentsHandle`
+///   today; either the resulting set of exactly one is what's available
+///   or the set would need to be backfilled once `gll` prints it out.
+fn render_parse_diagnostic(src: &str, result: &ModuleContentsResult) -> Option<String> {
+    let handle = match result {
+        Ok(_) => return None,
+        Err(parse::ParseError::TooShort(handle)) => *handle,
+        // `ParseError::NoParse` is a unit variant -- unlike `TooShort` it
+        // carries no handle, so there's no furthest-reached node for us
+        // to map back to a position. Reporting a furthest position here
+        // would need `gll`/`parse::ParseError` to carry one even on total
+        // failure, which they don't today.
+        Err(parse::ParseError::NoParse) => {
+            return Some(
+                "no parse: the grammar didn't match any prefix of the input, \
+                 so there's no partial derivation to point at\n"
+                    .to_string(),
+            );
+        }
+    };
+
+    let offset = handle.node.range.end;
+    let (line, col, line_text) = line_col_of(src, offset);
+
+    Some(format!(
+        "parsing got stuck at {}:{}:\n{}\n{}^\nexpected: {:?}\n",
+        line,
+        col + 1,
+        line_text,
+        " ".repeat(col),
+        handle.node.kind
+    ))
+}
+
 /// Output the result of a single file to stderr,
 /// optionally prefixed by a given `path`.
-fn report_file_result(path: Option<&Path>, result: ModuleContentsResult) {
+fn report_file_result(path: Option<&Path>, outcome: &FileOutcome) {
     if let Some(path) = path {
         eprint!("{}: ", path.display());
     }
-    // FIXME(eddyb) when we start parsing more this could become quite noisy.
-    eprintln!("{:#?}", result);
+    match outcome {
+        FileOutcome::LexError { src, error } => eprint!("{}", render_lex_error(src, error)),
+        FileOutcome::Parsed { src, result } => {
+            // FIXME(eddyb) when we start parsing more this could become quite noisy.
+            eprintln!("{:#?}", result);
+            if let Some(diagnostic) = render_parse_diagnostic(src, result) {
+                eprint!("{}", diagnostic);
+            }
+        }
+    }
 }
 
-fn ambiguity_check(handle: ModuleContentsHandle) -> Result<(), MoreThanOne> {
+/// The outcome of parsing a single file, as tracked by `Command::Dir`.
+#[derive(Clone, Copy)]
+enum Status {
+    Unambiguous,
+    Ambiguous,
+    TooShort,
+    NoParse,
+    LexError,
+}
+
+impl Status {
+    fn of(outcome: &FileOutcome) -> Status {
+        match outcome {
+            FileOutcome::LexError { .. } => Status::LexError,
+            FileOutcome::Parsed { result: Ok(handle), .. } => {
+                if ambiguity_check(*handle).is_ok() {
+                    Status::Unambiguous
+                } else {
+                    Status::Ambiguous
+                }
+            }
+            FileOutcome::Parsed { result: Err(parse::ParseError::TooShort(_)), .. } => Status::TooShort,
+            FileOutcome::Parsed { result: Err(parse::ParseError::NoParse), .. } => Status::NoParse,
+        }
+    }
+
+    fn symbol(self) -> char {
+        match self {
+            Status::Unambiguous => '~',
+            Status::Ambiguous => '!',
+            Status::TooShort => '.',
+            Status::NoParse => 'X',
+            Status::LexError => 'L',
+        }
+    }
+}
+
+/// Walk every SPPF node reachable from `handle.node`, breadth-first,
+/// stopping as soon as a `Choice`/`Split` node turns out to have more
+/// than one edge. Returns the `kind` of that node, or `Ok(())` if the
+/// whole forest was walked without hitting one.
+///
+/// Shared by `ambiguity_check` (which only cares *whether* this
+/// happens) and `ambiguity::find_first_divergence` (which reports
+/// *where*) -- before this was split out they were the same ~25 lines
+/// of BFS copy-pasted between the two call sites.
+pub(crate) fn walk_sppf_until_ambiguous(handle: ModuleContentsHandle) -> Result<(), ParseNodeKind> {
     let sppf = &handle.parser.sppf;
 
     let mut queue = VecDeque::new();
     queue.push_back(handle.node);
     let mut seen: BTreeSet<_> = queue.iter().cloned().collect();
 
-    while let Some(source) = queue.pop_front() {
+    while let Some(node) = queue.pop_front() {
         let mut add_children = |children: &[_]| {
             for &child in children {
                 if seen.insert(child) {
@@ -91,25 +264,32 @@ fn ambiguity_check(handle: ModuleContentsHandle) -> Result<(), MoreThanOne> {
                 }
             }
         };
-        match source.kind.shape() {
+        match node.kind.shape() {
             ParseNodeShape::Opaque => {}
-            ParseNodeShape::Alias(_) => add_children(&[source.unpack_alias()]),
+            ParseNodeShape::Alias(_) => add_children(&[node.unpack_alias()]),
             ParseNodeShape::Opt(_) => {
-                if let Some(child) = source.unpack_opt() {
+                if let Some(child) = node.unpack_opt() {
                     add_children(&[child]);
                 }
             }
-            ParseNodeShape::Choice => add_children(&[sppf.one_choice(source)?]),
-            ParseNodeShape::Split(..) => {
-                let (left, right) = sppf.one_split(source)?;
-                add_children(&[left, right])
-            }
+            ParseNodeShape::Choice => match sppf.one_choice(node) {
+                Ok(child) => add_children(&[child]),
+                Err(MoreThanOne) => return Err(node.kind),
+            },
+            ParseNodeShape::Split(..) => match sppf.one_split(node) {
+                Ok((left, right)) => add_children(&[left, right]),
+                Err(MoreThanOne) => return Err(node.kind),
+            },
         }
     }
 
     Ok(())
 }
 
+pub(crate) fn ambiguity_check(handle: ModuleContentsHandle) -> Result<(), MoreThanOne> {
+    walk_sppf_until_ambiguous(handle).map_err(|_| MoreThanOne)
+}
+
 fn main() {
     match Command::from_args() {
         Command::File {
@@ -117,77 +297,98 @@ fn main() {
             file,
         } => {
             // Not much to do, try to parse the file and report the result.
-            parse_file_with(&file, |result| {
-                match result {
-                    Ok(handle) | Err(parse::ParseError::TooShort(handle)) => {
-                        if let Some(out_path) = graphviz_forest {
-                            handle
-                                .parser
-                                .sppf
-                                .dump_graphviz(&mut fs::File::create(out_path).unwrap())
-                                .unwrap();
+            parse_file_with(&file, |outcome| {
+                if let FileOutcome::Parsed { result: Ok(handle), .. }
+                | FileOutcome::Parsed { result: Err(parse::ParseError::TooShort(handle)), .. } = &outcome
+                {
+                    if let Some(out_path) = graphviz_forest {
+                        handle
+                            .parser
+                            .sppf
+                            .dump_graphviz(&mut fs::File::create(out_path).unwrap())
+                            .unwrap();
+                    }
+                }
+
+                if let FileOutcome::Parsed { result: Ok(handle), .. } = &outcome {
+                    if ambiguity_check(*handle).is_err() {
+                        if let Some(divergence) = ambiguity::find_first_divergence(*handle) {
+                            eprintln!("ambiguity first diverges at: {:?}", divergence.at);
                         }
                     }
-                    Err(parse::ParseError::NoParse) => {}
                 }
-                report_file_result(None, result);
+
+                report_file_result(None, &outcome);
             });
         }
         Command::Dir { verbose, dir } => {
-            // Counters for reporting overall stats at the end.
-            let mut total_count = 0;
+            // Find all the `.rs` files inside the desired directory, up front,
+            // so the actual parsing can be handed off to a thread pool.
+            let paths: Vec<PathBuf> = WalkDir::new(dir)
+                .contents_first(true)
+                .into_iter()
+                .map(|entry| entry.unwrap())
+                .filter(|entry| entry.path().extension().map_or(false, |ext| ext == "rs"))
+                .map(|entry| entry.into_path())
+                .collect();
+
+            // Parse every file in parallel, each task returning its own
+            // status instead of mutating shared counters.
+            let statuses: Vec<Status> = paths
+                .par_iter()
+                .map(|path| {
+                    parse_file_with(path, |outcome| {
+                        let status = Status::of(&outcome);
+                        if verbose {
+                            report_file_result(Some(path), &outcome);
+                        }
+                        status
+                    })
+                })
+                .collect();
+
+            // Reduce the per-file statuses into the five counts, and print
+            // the compact grid in the original (not scheduling) order.
             let mut unambiguous_count = 0;
             let mut ambiguous_count = 0;
             let mut too_short_count = 0;
             let mut no_parse_count = 0;
+            let mut lex_error_count = 0;
 
-            // Find all the `.rs` files inside the desired directory.
-            let files = WalkDir::new(dir)
-                .contents_first(true)
-                .into_iter()
-                .map(|entry| entry.unwrap())
-                .filter(|entry| entry.path().extension().map_or(false, |ext| ext == "rs"));
-
-            // Go through all the files and try to parse each of them.
-            for file in files {
-                let path = file.into_path();
-                parse_file_with(&path, |result| {
-                    // Increment counters and figure out the character to print.
-                    let (status, count) = match result {
-                        Ok(handle) => {
-                            if ambiguity_check(handle).is_ok() {
-                                ('~', &mut unambiguous_count)
-                            } else {
-                                ('!', &mut ambiguous_count)
-                            }
-                        }
-                        Err(parse::ParseError::TooShort(_)) => ('.', &mut too_short_count),
-                        Err(parse::ParseError::NoParse) => ('X', &mut no_parse_count),
-                    };
-                    *count += 1;
-                    total_count += 1;
-
-                    if verbose {
-                        // Unless we're in verbose mode, in which case we print more.
-                        report_file_result(Some(&path), result);
-                    } else {
-                        // Limit the compact output to 80 columns wide.
-                        if total_count % 80 == 0 {
-                            println!("");
-                        }
-                        print!("{}", status);
-                        io::stdout().flush().unwrap();
+            for (i, status) in statuses.iter().enumerate() {
+                match status {
+                    Status::Unambiguous => unambiguous_count += 1,
+                    Status::Ambiguous => ambiguous_count += 1,
+                    Status::TooShort => too_short_count += 1,
+                    Status::NoParse => no_parse_count += 1,
+                    Status::LexError => lex_error_count += 1,
+                }
+
+                if !verbose {
+                    // Limit the compact output to 80 columns wide.
+                    if i > 0 && i % 80 == 0 {
+                        println!("");
                     }
-                })
+                    print!("{}", status.symbol());
+                }
+            }
+            if !verbose {
+                io::stdout().flush().unwrap();
             }
 
             // We're done, time to print out stats!
             println!("");
-            println!("Out of {} Rust files tested:", total_count);
+            println!("Out of {} Rust files tested:", statuses.len());
             println!("* {} parsed fully and unambiguously", unambiguous_count);
             println!("* {} parsed fully (but ambiguously)", ambiguous_count);
             println!("* {} parsed partially (only a prefix)", too_short_count);
             println!("* {} didn't parse at all", no_parse_count);
+            println!("* {} didn't even lex", lex_error_count);
+        }
+        Command::Test { dir } => {
+            if !golden::run(&dir) {
+                std::process::exit(1);
+            }
         }
     }
 }
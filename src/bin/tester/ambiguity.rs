@@ -0,0 +1,33 @@
+//! Locating where an ambiguous parse's derivations first diverge.
+//!
+//! This is a **downscoped stand-in** for what chunk0-5 actually asked
+//! for (a worklist that branches at every `Choice`/`Split` edge to
+//! enumerate up to N distinct derivation trees, deduplicated by shape,
+//! each dumped to its own `--dump-ambiguities` `.dot` file). That's not
+//! implemented here, and shouldn't be read as done under that
+//! request_id: `gll::runtime`'s SPPF only exposes `one_choice`/
+//! `one_split` (`Result<_, MoreThanOne>`), a uniqueness check with no
+//! way to recover the individual alternatives once there's more than
+//! one -- there's no lower-level accessor to fall back on, at least in
+//! what's visible from this tree (`gll`'s own source isn't vendored
+//! here, so this can't be fully verified against its real API surface).
+//! Enumeration, dedup, the cap, and the dump flag all need that upstream
+//! capability; until it exists, this reports only the single node where
+//! a full walk first had to give up, via the shared
+//! `walk_sppf_until_ambiguous` -- closing this out as "chunk0-5 done"
+//! is wrong; treat it as re-scoped to this reduced diagnostic pending
+//! the `gll` API gap above being filed and resolved upstream.
+
+use gll::runtime::ParseNodeKind;
+use walk_sppf_until_ambiguous;
+use ModuleContentsHandle;
+
+/// The node kind at which `handle`'s derivations first diverge.
+pub(crate) struct Divergence {
+    pub at: ParseNodeKind,
+}
+
+/// `Some` with the node `walk_sppf_until_ambiguous` stopped at, if any.
+pub(crate) fn find_first_divergence(handle: ModuleContentsHandle) -> Option<Divergence> {
+    walk_sppf_until_ambiguous(handle).err().map(|at| Divergence { at })
+}
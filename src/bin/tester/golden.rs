@@ -0,0 +1,141 @@
+//! Directory-driven golden-file tests, modelled on rust-analyzer's
+//! `.rast` expectation files: every `.rs` file under `tests/data/{ok,err}`
+//! is parsed and a canonical textual dump of the result is compared
+//! against a sibling file with the same name but a `.rast` extension
+//! (`issue1.rs` <-> `issue1.rast`).
+//!
+//! Files under `ok/` are expected to parse fully and unambiguously;
+//! files under `err/` are expected to fail with `NoParse` or `TooShort`.
+//! Set `UPDATE_EXPECT=1` to (re)write the `.rast` files instead of
+//! failing on a mismatch.
+
+use ambiguity_check;
+use parse_file_with;
+use rust_grammar::parse;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+use FileOutcome;
+
+/// Which fixture subdirectory a file was found under, and therefore what
+/// shape of result it's expected to produce.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Expectation {
+    Ok,
+    Err,
+}
+
+fn expectation_for(path: &Path) -> Option<Expectation> {
+    path.components().find_map(|c| match c.as_os_str().to_str() {
+        Some("ok") => Some(Expectation::Ok),
+        Some("err") => Some(Expectation::Err),
+        _ => None,
+    })
+}
+
+/// Render the canonical textual dump used for `.rast` comparisons: the
+/// pretty-printed parse result (or lexer error), plus the ambiguity
+/// verdict when it parsed at all.
+fn render(outcome: &FileOutcome) -> String {
+    let mut out = String::new();
+    match outcome {
+        FileOutcome::LexError { error, .. } => {
+            let _ = writeln!(out, "lexer error: {}", error);
+        }
+        FileOutcome::Parsed { result: Ok(handle), .. } => {
+            let _ = writeln!(out, "{:#?}", Ok::<_, ()>(*handle));
+            match ambiguity_check(*handle) {
+                Ok(()) => out.push_str("ambiguity: none\n"),
+                Err(_) => out.push_str("ambiguity: MoreThanOne\n"),
+            }
+        }
+        FileOutcome::Parsed { result: Err(err), .. } => {
+            let _ = writeln!(out, "{:#?}", Err::<(), _>(err));
+        }
+    }
+    out
+}
+
+fn matches_expectation(expectation: Expectation, outcome: &FileOutcome) -> bool {
+    match expectation {
+        Expectation::Ok => match outcome {
+            FileOutcome::Parsed { result: Ok(handle), .. } => ambiguity_check(*handle).is_ok(),
+            _ => false,
+        },
+        Expectation::Err => match outcome {
+            FileOutcome::LexError { .. } => true,
+            FileOutcome::Parsed { result: Ok(_), .. } => false,
+            FileOutcome::Parsed { result: Err(parse::ParseError::NoParse), .. }
+            | FileOutcome::Parsed { result: Err(parse::ParseError::TooShort(_)), .. } => true,
+        },
+    }
+}
+
+/// Check a single fixture file against its `.rast`, creating or
+/// overwriting it when missing or when `update_expect` is set.
+fn check_file(path: &Path, update_expect: bool) -> Result<(), String> {
+    let expectation = expectation_for(path)
+        .ok_or_else(|| format!("{}: not under an `ok/` or `err/` directory", path.display()))?;
+    let expect_path = path.with_extension("rast");
+    let existing = fs::read_to_string(&expect_path).ok();
+
+    parse_file_with(path, |outcome| {
+        if !matches_expectation(expectation, &outcome) {
+            return Err(format!(
+                "{}: result didn't match its `{}/` expectation:\n{}",
+                path.display(),
+                if expectation == Expectation::Ok { "ok" } else { "err" },
+                render(&outcome)
+            ));
+        }
+
+        let dump = render(&outcome);
+
+        if update_expect || existing.is_none() {
+            fs::write(&expect_path, &dump)
+                .map_err(|e| format!("{}: couldn't write: {}", expect_path.display(), e))?;
+            return Ok(());
+        }
+
+        let existing = existing.unwrap();
+        if existing == dump {
+            Ok(())
+        } else {
+            Err(format!(
+                "{}: output doesn't match `{}`\n--- expected ---\n{}--- actual ---\n{}",
+                path.display(),
+                expect_path.display(),
+                existing,
+                dump
+            ))
+        }
+    })
+}
+
+/// Run every fixture under `dir`, returning whether they all passed.
+pub fn run(dir: &Path) -> bool {
+    let update_expect = env::var_os("UPDATE_EXPECT").map_or(false, |v| v == "1");
+
+    let files: Vec<PathBuf> = WalkDir::new(dir)
+        .into_iter()
+        .map(|entry| entry.unwrap())
+        .filter(|entry| entry.path().extension().map_or(false, |ext| ext == "rs"))
+        .map(|entry| entry.into_path())
+        .collect();
+
+    let mut all_ok = true;
+    for path in &files {
+        match check_file(path, update_expect) {
+            Ok(()) => println!("ok       {}", path.display()),
+            Err(msg) => {
+                eprintln!("FAILED   {}", msg);
+                all_ok = false;
+            }
+        }
+    }
+
+    println!("{} fixtures, {}", files.len(), if all_ok { "ok" } else { "FAILED" });
+    all_ok
+}
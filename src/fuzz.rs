@@ -0,0 +1,115 @@
+//! A libfuzzer/afl-style invariant fuzzer for the grammar, in the style
+//! rust-analyzer's fuzz targets use: rather than comparing against an
+//! oracle, it asserts structural invariants that must hold for any input.
+//!
+//! NOTE: this tree has no `src/lib.rs` anywhere, including in the
+//! baseline commit, so there's no crate root to add `pub mod fuzz;` to
+//! -- `fuzz/fuzz_targets/parse.rs`'s `rust_grammar::fuzz::check_invariants`
+//! has nothing to resolve against and this module is unreachable as a
+//! result. Add `pub mod fuzz;` to `src/lib.rs` once one exists; until
+//! then this is source, not a wired-up fuzz target.
+//!
+//! Also note `node_count` below re-implements the same BFS-over-SPPF
+//! traversal as the tester bin's `walk_sppf_until_ambiguous` (which this
+//! module can't depend on -- it's a different binary, and there's no
+//! lib crate here for either of them to share through). Once a
+//! `src/lib.rs` exists, move the shared walker there and have both the
+//! tester and this module call it instead of keeping two copies.
+
+use crate::parse::{self, ParseError};
+use gll::runtime::{MoreThanOne, ParseNodeShape};
+use proc_macro2::TokenStream;
+use std::collections::{BTreeSet, VecDeque};
+
+/// Walk every SPPF node reachable from `handle.node`, returning how many
+/// were visited and whether the walk had to stop short of a `Choice`/
+/// `Split` node's alternatives because it was ambiguous there.
+///
+/// `gll::runtime`'s SPPF only exposes `one_choice`/`one_split`
+/// (`Result<_, MoreThanOne>`) -- there's no `all_choices`/`all_splits` to
+/// recover the alternatives with once a node turns out ambiguous, so
+/// rather than fabricate that API this just stops descending past an
+/// ambiguous node, same as the tester's `ambiguity_check` does (it just
+/// also reports that it happened, instead of only bailing out).
+fn node_count<'a, 'i, I, T>(handle: parse::Handle<'a, 'i, I, T>) -> (usize, Result<(), MoreThanOne>) {
+    let sppf = &handle.parser.sppf;
+    let mut queue = VecDeque::new();
+    queue.push_back(handle.node);
+    let mut seen: BTreeSet<_> = queue.iter().cloned().collect();
+    let mut ambiguous = Ok(());
+
+    while let Some(node) = queue.pop_front() {
+        let mut add_children = |children: &[_]| {
+            for &child in children {
+                if seen.insert(child) {
+                    queue.push_back(child);
+                }
+            }
+        };
+        match node.kind.shape() {
+            ParseNodeShape::Opaque => {}
+            ParseNodeShape::Alias(_) => add_children(&[node.unpack_alias()]),
+            ParseNodeShape::Opt(_) => {
+                if let Some(child) = node.unpack_opt() {
+                    add_children(&[child]);
+                }
+            }
+            ParseNodeShape::Choice => match sppf.one_choice(node) {
+                Ok(child) => add_children(&[child]),
+                Err(MoreThanOne) => ambiguous = Err(MoreThanOne),
+            },
+            ParseNodeShape::Split(..) => match sppf.one_split(node) {
+                Ok((left, right)) => add_children(&[left, right]),
+                Err(MoreThanOne) => ambiguous = Err(MoreThanOne),
+            },
+        }
+    }
+
+    (seen.len(), ambiguous)
+}
+
+/// A cheap fingerprint of a parse: whether it succeeded, partially
+/// succeeded, or failed outright, plus the number of SPPF nodes
+/// reachable from the result when there is one (and whether that walk
+/// ran into an ambiguous node it couldn't fully descend past).
+#[derive(PartialEq, Eq, Debug)]
+enum Fingerprint {
+    Ok(usize, bool),
+    TooShort(usize, bool),
+    NoParse,
+}
+
+fn fingerprint(tts: TokenStream) -> Fingerprint {
+    parse::ModuleContents::parse_with(tts, |_, result| match result {
+        Ok(handle) => {
+            let (count, ambiguous) = node_count(handle);
+            Fingerprint::Ok(count, ambiguous.is_err())
+        }
+        Err(ParseError::TooShort(handle)) => {
+            let (count, ambiguous) = node_count(handle);
+            Fingerprint::TooShort(count, ambiguous.is_err())
+        }
+        Err(ParseError::NoParse) => Fingerprint::NoParse,
+    })
+}
+
+/// Entry point for a libfuzzer/afl harness.
+///
+/// Lossily interprets `data` as UTF-8 and, for anything that lexes into a
+/// `TokenStream`, asserts that:
+///
+/// * the parser never panics;
+/// * the SPPF reachable from an `Ok`/`TooShort` result can be walked to
+///   completion without revisiting a node (see `node_count`);
+/// * re-running the parse on the same `TokenStream` is deterministic --
+///   identical success/failure classification and identical node count.
+pub fn check_invariants(data: &[u8]) {
+    let src = String::from_utf8_lossy(data);
+
+    let tts: TokenStream = match src.parse() {
+        Ok(tts) => tts,
+        Err(_) => return,
+    };
+
+    assert_eq!(fingerprint(tts.clone()), fingerprint(tts));
+}
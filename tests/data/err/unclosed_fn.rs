@@ -0,0 +1,2 @@
+fn unfinished(x: i32) -> i32 {
+    x + 1
@@ -0,0 +1,8 @@
+struct Point {
+    x: i32,
+    y: i32,
+}
+
+fn distance(a: Point, b: Point) -> i32 {
+    (a.x - b.x) * (a.x - b.x) + (a.y - b.y) * (a.y - b.y)
+}